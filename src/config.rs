@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::sync::{RwLock, RwLockReadGuard};
+use std::time::Duration;
 
 use once_cell::sync::OnceCell;
 
@@ -42,25 +44,6 @@ pub const MAX_FUZZ_TIME: u64 = 600;
 
 pub const MAX_CONTEXT_APIS: usize = 100;
 
-// recover the report of UBSan, or we can use UBSAN_OPTIONS=symbolize=1:print_stacktrace=1:halt_on_error=1 instead.
-pub const SANITIZER_FLAGS: [&str; 7] = [
-    "-fsanitize=fuzzer",
-    "-g",
-    "-O1",
-    "-fsanitize=address,undefined",
-    "-ftrivial-auto-var-init=zero",
-    "-fsanitize-trap=undefined",
-    "-fno-sanitize-recover=undefined",
-];
-
-pub const FUZZER_FLAGS: [&str; 5] = [
-    "-fsanitize=fuzzer",
-    "-O1",
-    "-g",
-    "-fsanitize=address,undefined",
-    "-ftrivial-auto-var-init=zero",
-];
-
 pub const COVERAGE_FLAGS: [&str; 9] = [
     "-g",
     "-fsanitize=fuzzer",
@@ -75,6 +58,137 @@ pub const COVERAGE_FLAGS: [&str; 9] = [
 
 pub const ASAN_OPTIONS: [&str; 2] = ["exitcode=168", "alloc_dealloc_mismatch=0"];
 
+/// A sanitizer that a fuzz target can be compiled against. Selected per-run via `--sanitizer`
+/// (see `Config::sanitizers`) or pinned per-project via `LibConfig::sanitizers`, in place of the
+/// old hardcoded ASan+UBSan-only flag arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, serde::Serialize, serde::Deserialize)]
+pub enum Sanitizer {
+    Address,
+    Undefined,
+    Memory,
+    Thread,
+    Leak,
+}
+
+impl Sanitizer {
+    /// The name clang expects after `-fsanitize=`.
+    fn flag_name(&self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+            Sanitizer::Memory => "memory",
+            Sanitizer::Thread => "thread",
+            Sanitizer::Leak => "leak",
+        }
+    }
+
+    /// The `*_OPTIONS` environment variable this sanitizer reads at runtime.
+    fn options_env_var(&self) -> &'static str {
+        match self {
+            Sanitizer::Address => "ASAN_OPTIONS",
+            Sanitizer::Undefined => "UBSAN_OPTIONS",
+            Sanitizer::Memory => "MSAN_OPTIONS",
+            Sanitizer::Thread => "TSAN_OPTIONS",
+            Sanitizer::Leak => "LSAN_OPTIONS",
+        }
+    }
+
+    /// Whether `-fsanitize-trap=`/`-fno-sanitize-recover=` can be applied to this sanitizer. Only
+    /// UBSan's checks support trap mode; the others (ASan in particular) route crash handling
+    /// through their runtime, which is what lets `ASAN_OPTIONS` (see `sanitizer_options_env`)
+    /// report a distinct crash exit code. Trapping them would bypass that runtime entirely.
+    fn supports_trap(&self) -> bool {
+        matches!(self, Sanitizer::Undefined)
+    }
+}
+
+impl std::fmt::Display for Sanitizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.flag_name())
+    }
+}
+
+/// The crate's historical default: ASan+UBSan, recovering the report of UBSan (or use
+/// `UBSAN_OPTIONS=symbolize=1:print_stacktrace=1:halt_on_error=1` instead).
+pub const DEFAULT_SANITIZERS: [Sanitizer; 2] = [Sanitizer::Address, Sanitizer::Undefined];
+
+fn sanitize_flag_value(sanitizers: &[Sanitizer]) -> String {
+    sanitizers.iter().map(Sanitizer::flag_name).collect::<Vec<_>>().join(",")
+}
+
+/// Compiler/linker flags for sanitizer-instrumented fuzz targets, generated from the selected
+/// `sanitizers` instead of baking in ASan+UBSan. Trap/no-recover mode is only added for the
+/// sanitizers that support it (see `Sanitizer::supports_trap`), so selecting ASan alongside UBSan
+/// doesn't trap ASan out from under its runtime-based crash recovery.
+pub fn sanitizer_flags(sanitizers: &[Sanitizer]) -> Vec<String> {
+    let combined = sanitize_flag_value(sanitizers);
+    let mut flags = vec![
+        "-fsanitize=fuzzer".to_string(),
+        "-g".to_string(),
+        "-O1".to_string(),
+        format!("-fsanitize={combined}"),
+        "-ftrivial-auto-var-init=zero".to_string(),
+    ];
+    let trappable: Vec<Sanitizer> = sanitizers.iter().copied().filter(Sanitizer::supports_trap).collect();
+    if !trappable.is_empty() {
+        let trappable = sanitize_flag_value(&trappable);
+        flags.push(format!("-fsanitize-trap={trappable}"));
+        flags.push(format!("-fno-sanitize-recover={trappable}"));
+    }
+    flags
+}
+
+/// Compiler/linker flags for the plain (non-minimized) fuzzer build.
+pub fn fuzzer_flags(sanitizers: &[Sanitizer]) -> Vec<String> {
+    let combined = sanitize_flag_value(sanitizers);
+    vec![
+        "-fsanitize=fuzzer".to_string(),
+        "-O1".to_string(),
+        "-g".to_string(),
+        format!("-fsanitize={combined}"),
+        "-ftrivial-auto-var-init=zero".to_string(),
+    ]
+}
+
+/// Which sanitizers to build `target` with: a project's `LibConfig::sanitizers` override takes
+/// priority over the run-wide `--sanitizer` selection.
+///
+/// This, `sanitizer_flags`, and `sanitizer_options_env` are the compiler-flag/env-var side of
+/// sanitizer selection; the module that actually shells out to the compiler and sets these
+/// variables around a fuzz run is `execution::Executor`, which isn't part of this tree -- wire
+/// these in there rather than re-deriving flags/env from `Config`/`LibConfig` directly.
+pub fn get_active_sanitizers(lib_config: &LibConfig) -> Vec<Sanitizer> {
+    lib_config
+        .sanitizers
+        .clone()
+        .unwrap_or_else(|| get_config().sanitizers.clone())
+}
+
+/// Build the `<SAN>_OPTIONS` environment variables for every selected sanitizer, folding in the
+/// `asan_option`/`rss_limit_mb` overrides from `LibConfig` the same way the old hardcoded
+/// `ASAN_OPTIONS` did, so the existing crash-recovery logic keeps working regardless of which
+/// sanitizers are active.
+pub fn sanitizer_options_env(sanitizers: &[Sanitizer], lib_config: &LibConfig) -> Vec<(String, String)> {
+    sanitizers
+        .iter()
+        .map(|sanitizer| {
+            let mut opts: Vec<String> = match sanitizer {
+                Sanitizer::Address => ASAN_OPTIONS.iter().map(|s| s.to_string()).collect(),
+                _ => Vec::new(),
+            };
+            if *sanitizer == Sanitizer::Address {
+                if let Some(asan_option) = &lib_config.asan_option {
+                    opts.push(asan_option.clone());
+                }
+            }
+            if let Some(rss_limit) = lib_config.rss_limit_mb {
+                opts.push(format!("hard_rss_limit_mb={rss_limit}"));
+            }
+            (sanitizer.options_env_var().to_string(), opts.join(":"))
+        })
+        .collect()
+}
+
 pub fn get_openai_model_name() -> String {
     OPENAI_MODEL_NAME.get().unwrap().to_string()
 }
@@ -125,6 +239,11 @@ pub fn get_handler_type() -> HandlerType {
     config.handler_type.clone()
 }
 
+pub fn get_cache_enabled() -> bool {
+    let config = CONFIG_INSTANCE.get().unwrap().read().unwrap();
+    config.cache
+}
+
 pub fn get_minimize_compile_flag() -> &'static str {
     static MIN_FLAG: OnceCell<String> = OnceCell::new();
     MIN_FLAG.get_or_init(|| {
@@ -163,6 +282,174 @@ pub enum HandlerType {
     Openai,
     /// 使用HTTP客户端
     Http,
+    /// 录制/回放客户端，用于离线确定性测试
+    Replay,
+}
+
+/// Configuration for a single LLM backend, tagged by `type` so a `clients.yaml` file can
+/// describe several backends (e.g. one OpenAI and one self-hosted Claude deployment) side by
+/// side. `request::init()` picks the variant whose `models` list contains the configured model
+/// name and hands back the matching `Handler`, mirroring aichat's client registry.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum ClientConfig {
+    #[serde(rename = "openai")]
+    OpenAI {
+        api_key: Option<String>,
+        api_base: Option<String>,
+        organization_id: Option<String>,
+        models: Vec<String>,
+        extra: Option<ClientExtraConfig>,
+    },
+    #[serde(rename = "azure-openai")]
+    AzureOpenai {
+        api_key: Option<String>,
+        api_base: String,
+        api_version: Option<String>,
+        /// The Azure deployment this backend routes to. Azure's chat-completions API is reached
+        /// via a deployment-scoped path (`/openai/deployments/{deployment_id}/chat/completions`)
+        /// rather than by the request body's `model` field, so this is required for every
+        /// request to land anywhere.
+        deployment_id: String,
+        models: Vec<String>,
+        extra: Option<ClientExtraConfig>,
+    },
+    #[serde(rename = "claude")]
+    Claude {
+        api_key: Option<String>,
+        api_base: Option<String>,
+        models: Vec<String>,
+        extra: Option<ClientExtraConfig>,
+    },
+    #[serde(rename = "openai-compatible")]
+    OpenAICompatible {
+        api_key: Option<String>,
+        api_base: String,
+        models: Vec<String>,
+        extra: Option<ClientExtraConfig>,
+    },
+    /// A local GGUF model served in-process via `llama.cpp`, for air-gapped fuzzing runs that
+    /// can't or shouldn't reach a hosted API.
+    #[serde(rename = "llama-cpp")]
+    LlamaCpp {
+        model_path: String,
+        /// Chat template applied to render `ChatCompletionRequestMessage`s into a prompt string.
+        /// Falls back to the template baked into the GGUF file's metadata when unset.
+        chat_template: Option<String>,
+        models: Vec<String>,
+    },
+}
+
+impl ClientConfig {
+    /// The model names this backend should be selected for.
+    pub fn models(&self) -> &[String] {
+        match self {
+            ClientConfig::OpenAI { models, .. } => models,
+            ClientConfig::AzureOpenai { models, .. } => models,
+            ClientConfig::Claude { models, .. } => models,
+            ClientConfig::OpenAICompatible { models, .. } => models,
+            ClientConfig::LlamaCpp { models, .. } => models,
+        }
+    }
+
+    /// The backend's proxy/timeout/header overrides, if it has any (`LlamaCpp` has none, since
+    /// it never makes a network request).
+    pub fn extra(&self) -> Option<&ClientExtraConfig> {
+        match self {
+            ClientConfig::OpenAI { extra, .. } => extra.as_ref(),
+            ClientConfig::AzureOpenai { extra, .. } => extra.as_ref(),
+            ClientConfig::Claude { extra, .. } => extra.as_ref(),
+            ClientConfig::OpenAICompatible { extra, .. } => extra.as_ref(),
+            ClientConfig::LlamaCpp { .. } => None,
+        }
+    }
+}
+
+/// Per-backend overrides for proxy, timeouts, organization, and extra default headers, so users
+/// behind different networks or slower self-hosted endpoints can adjust without recompiling.
+/// Mirrors aichat's per-client `extra` block.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ClientExtraConfig {
+    /// `http://` or `socks5://` proxy URL. Falls back to `HTTPS_PROXY`/`ALL_PROXY` env vars,
+    /// then to no proxy, when unset.
+    pub proxy: Option<String>,
+    pub connect_timeout: Option<u64>,
+    pub timeout: Option<u64>,
+    pub organization_id: Option<String>,
+    pub extra_headers: Option<HashMap<String, String>>,
+}
+
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+pub const DEFAULT_TIMEOUT_SECS: u64 = 180;
+
+/// Build a `reqwest::Client` honoring a backend's `extra` config, falling back to the crate's
+/// historical defaults (10s connect / 180s request timeout) when unset. With no configured
+/// proxy, `HTTPS_PROXY` and then `ALL_PROXY` are used if set; otherwise no proxy.
+pub fn build_http_client(extra: Option<&ClientExtraConfig>) -> eyre::Result<reqwest::Client> {
+    let mut builder = reqwest::ClientBuilder::new()
+        .connect_timeout(Duration::from_secs(
+            extra.and_then(|e| e.connect_timeout).unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+        ))
+        .timeout(Duration::from_secs(
+            extra.and_then(|e| e.timeout).unwrap_or(DEFAULT_TIMEOUT_SECS),
+        ));
+
+    let proxy = extra
+        .and_then(|e| e.proxy.clone())
+        .or_else(|| std::env::var("HTTPS_PROXY").ok())
+        .or_else(|| std::env::var("ALL_PROXY").ok());
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+
+    if let Some(headers) = extra.and_then(|e| e.extra_headers.as_ref()) {
+        let mut header_map = reqwest::header::HeaderMap::new();
+        for (key, value) in headers {
+            header_map.insert(
+                reqwest::header::HeaderName::from_bytes(key.as_bytes())?,
+                reqwest::header::HeaderValue::from_str(value)?,
+            );
+        }
+        builder = builder.default_headers(header_map);
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Path of the optional multi-backend client config file, relative to the working directory.
+pub const CLIENTS_CONFIG_PATH: &str = "clients.yaml";
+
+/// Load the configured backends from `clients.yaml`, if present. Absence is not an error: a
+/// project that only ever talked to OpenAI keeps working off `OPENAI_*` env vars alone.
+pub fn get_client_configs() -> &'static Vec<ClientConfig> {
+    static CLIENT_CONFIGS: OnceCell<Vec<ClientConfig>> = OnceCell::new();
+    CLIENT_CONFIGS.get_or_init(|| {
+        let path = std::path::Path::new(CLIENTS_CONFIG_PATH);
+        if !path.exists() {
+            return Vec::new();
+        }
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                log::warn!("Could not read {CLIENTS_CONFIG_PATH}: {e}");
+                return Vec::new();
+            }
+        };
+        match serde_yaml::from_str(&text) {
+            Ok(clients) => clients,
+            Err(e) => {
+                log::warn!("Could not parse {CLIENTS_CONFIG_PATH}: {e}");
+                Vec::new()
+            }
+        }
+    })
+}
+
+/// Find the backend config whose `models` list contains the currently configured model name.
+pub fn get_client_config_for_model(model: &str) -> Option<&'static ClientConfig> {
+    get_client_configs()
+        .iter()
+        .find(|client| client.models().iter().any(|m| m == model))
 }
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
@@ -202,6 +489,16 @@ pub struct Config {
     /// Select the handler type for LLM requests
     #[arg(long = "handler", default_value = "openai")]
     pub handler_type: HandlerType,
+    /// Cache LLM responses to disk and reuse them for prompts already answered in a prior run of
+    /// this campaign. Off by default since it's only correct when *resuming* a campaign after a
+    /// restart -- a fresh campaign re-asking the same prompt wants `n_sample` newly-sampled
+    /// completions, not the first run's cached ones.
+    #[arg(long, default_value = "false")]
+    pub cache: bool,
+    /// Sanitizers to compile the fuzz target against. Repeat the flag or pass multiple values to
+    /// combine them, e.g. `--sanitizer thread --sanitizer undefined` for TSan+UBSan.
+    #[arg(long = "sanitizer", value_enum, num_args = 1.., default_values_t = DEFAULT_SANITIZERS)]
+    pub sanitizers: Vec<Sanitizer>,
 }
 
 impl Config {
@@ -219,7 +516,9 @@ impl Config {
             recheck: false,
             fuzzer_run: false,
             disable_power_schedule: false,
-            handler_type: HandlerType::Openai,
+            handler_type: HandlerType::Replay,
+            cache: false,
+            sanitizers: DEFAULT_SANITIZERS.to_vec(),
         };
         let _ = CONFIG_INSTANCE.set(RwLock::new(config));
         crate::init_debug_logger().unwrap();
@@ -259,6 +558,10 @@ pub struct LibConfig {
     pub disable_fmemopen: Option<bool>,
     /// Memory limit passed to libfuzzer
     pub rss_limit_mb: Option<usize>,
+    /// Per-project override of which sanitizers to build with, taking priority over the run-wide
+    /// `--sanitizer` selection. Useful for libraries where a given sanitizer class matters most,
+    /// e.g. TSan for libvpx/libaom threading bugs.
+    pub sanitizers: Option<Vec<Sanitizer>>,
 }
 
 impl LibConfig {
@@ -422,3 +725,42 @@ pub fn get_user_chat_template() -> String {
     }
     template
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitizer_flags_traps_only_undefined() {
+        let flags = sanitizer_flags(&[Sanitizer::Address, Sanitizer::Undefined]);
+        assert!(flags.contains(&"-fsanitize=address,undefined".to_string()));
+        assert!(flags.contains(&"-fsanitize-trap=undefined".to_string()));
+        assert!(flags.contains(&"-fno-sanitize-recover=undefined".to_string()));
+        // ASan doesn't support trap mode, so it must never show up in the trap/no-recover flags.
+        assert!(!flags.iter().any(|f| f.contains("trap=address") || f.contains("recover=address")));
+    }
+
+    #[test]
+    fn test_sanitizer_flags_no_trap_flags_without_a_trappable_sanitizer() {
+        let flags = sanitizer_flags(&[Sanitizer::Address]);
+        assert!(!flags.iter().any(|f| f.starts_with("-fsanitize-trap") || f.starts_with("-fno-sanitize-recover")));
+    }
+
+    #[test]
+    fn test_sanitizer_options_env_folds_in_lib_config_overrides() {
+        let lib_config = LibConfig {
+            asan_option: Some("detect_leaks=0".to_string()),
+            rss_limit_mb: Some(4096),
+            ..Default::default()
+        };
+        let env = sanitizer_options_env(&[Sanitizer::Address, Sanitizer::Undefined], &lib_config);
+
+        let (_, asan_opts) = env.iter().find(|(var, _)| var == "ASAN_OPTIONS").unwrap();
+        assert!(asan_opts.contains("detect_leaks=0"));
+        assert!(asan_opts.contains("hard_rss_limit_mb=4096"));
+
+        let (_, ubsan_opts) = env.iter().find(|(var, _)| var == "UBSAN_OPTIONS").unwrap();
+        assert!(!ubsan_opts.contains("detect_leaks"), "asan_option must not leak into other sanitizers");
+        assert!(ubsan_opts.contains("hard_rss_limit_mb=4096"), "rss_limit_mb applies to every sanitizer");
+    }
+}
@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+use crate::{config, deopt::Deopt, program::Program};
+
+use super::{prompt::Prompt, Handler};
+
+/// Wraps any `Handler` with a persistent, content-addressable cache so re-running the same
+/// prompt against the same model/temperature/n_sample doesn't re-issue the LLM request. Only
+/// `init()` applies this wrapper, and only when `--cache` is passed -- it's meant for resuming a
+/// campaign after a restart without re-spending tokens, not as the default for every request,
+/// since the same prompt recurring mid-campaign is supposed to get a fresh `n_sample` draw.
+pub struct CachingHandler {
+    inner: Box<dyn Handler>,
+}
+
+impl CachingHandler {
+    pub fn new(inner: Box<dyn Handler>) -> Self {
+        Self { inner }
+    }
+}
+
+fn render_prompt_text(prompt: &Prompt) -> String {
+    serde_json::to_string(&prompt.to_chatgpt_message()).unwrap_or_default()
+}
+
+/// Hash the fully-rendered prompt text together with the model name, temperature, and
+/// `n_sample`. Including the sampling parameters in the key is the critical invariant so a cache
+/// entry never bleeds across different models or temperatures.
+fn cache_key(prompt_text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prompt_text.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(config::get_openai_model_name().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(config::get_config().temperature.to_string().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(config::get_config().n_sample.to_string().as_bytes());
+    hasher.finalize().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn cache_file(deopt: &Deopt, key: &str, suffix: &str) -> eyre::Result<PathBuf> {
+    let dir = deopt.get_library_data_dir()?.join("llm_cache");
+    if !dir.exists() {
+        std::fs::create_dir_all(&dir)?;
+    }
+    Ok(dir.join(format!("{key}.{suffix}")))
+}
+
+impl Handler for CachingHandler {
+    fn generate(&self, prompt: &Prompt) -> eyre::Result<Vec<Program>> {
+        let deopt = Deopt::new(config::get_library_name())?;
+        let key = cache_key(&render_prompt_text(prompt));
+        let path = cache_file(&deopt, &key, "programs.json")?;
+
+        if let Ok(text) = std::fs::read_to_string(&path) {
+            if let Ok(programs) = serde_json::from_str::<Vec<Program>>(&text) {
+                log::info!("LLM cache hit for {key}");
+                return Ok(programs);
+            }
+        }
+
+        let programs = self.inner.generate(prompt)?;
+        match serde_json::to_string(&programs) {
+            Ok(text) => {
+                if let Err(e) = std::fs::write(&path, text) {
+                    log::warn!("Could not persist LLM cache entry {key}: {e}");
+                }
+            }
+            Err(e) => log::warn!("Could not serialize generated programs for caching: {e}"),
+        }
+        Ok(programs)
+    }
+
+    fn generate_json(&self, prompt: String, deopt: &Deopt) -> eyre::Result<serde_json::Value> {
+        let key = cache_key(&prompt);
+        let path = cache_file(deopt, &key, "json")?;
+
+        if let Ok(text) = std::fs::read_to_string(&path) {
+            if let Ok(value) = serde_json::from_str::<serde_json::Value>(&text) {
+                log::info!("LLM cache hit for {key}");
+                return Ok(value);
+            }
+        }
+
+        let value = self.inner.generate_json(prompt, deopt)?;
+        if let Err(e) = std::fs::write(&path, value.to_string()) {
+            log::warn!("Could not persist LLM cache entry {key}: {e}");
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key_stable_for_identical_inputs() {
+        config::Config::init_test("cJSON");
+        let _ = config::OPENAI_MODEL_NAME.set("test-model".to_string());
+
+        assert_eq!(cache_key("same prompt"), cache_key("same prompt"));
+    }
+
+    #[test]
+    fn test_cache_key_changes_with_prompt_and_sampling_params() {
+        config::Config::init_test("cJSON");
+        let _ = config::OPENAI_MODEL_NAME.set("test-model".to_string());
+
+        let key = cache_key("prompt a");
+        assert_ne!(key, cache_key("prompt b"), "different prompt text must not collide");
+
+        let before_temp_change = cache_key("prompt a");
+        {
+            let mut cfg = config::CONFIG_INSTANCE.get().unwrap().write().unwrap();
+            cfg.temperature += 0.1;
+        }
+        assert_ne!(
+            before_temp_change,
+            cache_key("prompt a"),
+            "changing temperature must not bleed the cache across sampling params"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    config::{self, ClientConfig},
+    deopt::Deopt,
+    program::Program,
+    FuzzerError,
+};
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestUserMessageArgs};
+use eyre::Result;
+use futures::future::join_all;
+use serde_json::{json, Value};
+
+use super::{
+    openai::{strip_code_wrapper, strip_json_code_fence, TokenUsage},
+    Handler,
+};
+
+const DEFAULT_CLAUDE_API_BASE: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// `Handler` for the Anthropic Messages API. Unlike the OpenAI family, Claude has its own
+/// request/response shape (`content` is a list of typed blocks rather than a single string,
+/// `system` is a top-level field rather than a message with role `system`, and auth is an
+/// `x-api-key` header rather than `Authorization: Bearer`), so this talks to it directly over
+/// `reqwest` instead of going through `async_openai`.
+pub struct ClaudeHandler {
+    http: reqwest::Client,
+    api_base: String,
+    api_key: String,
+}
+
+impl ClaudeHandler {
+    pub fn new(config: &ClientConfig) -> Result<Self> {
+        let ClientConfig::Claude { api_key, api_base, .. } = config else {
+            eyre::bail!("ClaudeHandler constructed from a non-claude client config");
+        };
+        let api_key = api_key
+            .clone()
+            .or_else(|| std::env::var("ANTHROPIC_API_KEY").ok())
+            .ok_or_else(|| eyre::eyre!("Missing Claude api_key (set it in clients.yaml or ANTHROPIC_API_KEY)"))?;
+        let http = config::build_http_client(config.extra())?;
+        Ok(Self {
+            http,
+            api_base: api_base.clone().unwrap_or_else(|| DEFAULT_CLAUDE_API_BASE.to_string()),
+            api_key,
+        })
+    }
+
+    /// Split a chat message list into Claude's top-level `system` string plus its `messages`
+    /// array, since the Messages API doesn't accept a `system`-role message.
+    fn to_claude_messages(msgs: &[ChatCompletionRequestMessage]) -> (Option<String>, Vec<Value>) {
+        let mut system = None;
+        let mut claude_msgs = Vec::new();
+        for msg in msgs {
+            let v = serde_json::to_value(msg).unwrap_or_default();
+            let role = v["role"].as_str().unwrap_or("user").to_string();
+            let content = v["content"].as_str().unwrap_or_default().to_string();
+            if role == "system" {
+                system = Some(content);
+            } else {
+                let role = if role == "assistant" { "assistant" } else { "user" };
+                claude_msgs.push(json!({"role": role, "content": content}));
+            }
+        }
+        (system, claude_msgs)
+    }
+
+    async fn messages(&self, msgs: &[ChatCompletionRequestMessage]) -> Result<(String, TokenUsage)> {
+        let (system, claude_msgs) = Self::to_claude_messages(msgs);
+        let mut body = json!({
+            "model": config::get_openai_model_name(),
+            "max_tokens": config::MAX_TOKENS,
+            "temperature": config::get_config().temperature,
+            "messages": claude_msgs,
+        });
+        if let Some(system) = system {
+            body["system"] = json!(system);
+        }
+
+        let mut response: Value = Value::Null;
+        for retry in 0..config::RETRY_N {
+            let result = self
+                .http
+                .post(format!("{}/messages", self.api_base))
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION)
+                .json(&body)
+                .send()
+                .await;
+            match result {
+                Ok(resp) if resp.status().is_success() => {
+                    response = resp.json().await?;
+                    break;
+                }
+                Ok(resp) if resp.status().as_u16() == 429 || resp.status().is_server_error() => {
+                    let delay = crate::request::openai::backoff_delay(retry);
+                    log::warn!(
+                        "Claude request failed with {} (attempt {}/{}), backing off {:.1}s before retry",
+                        resp.status(),
+                        retry + 1,
+                        config::RETRY_N,
+                        delay.as_secs_f32()
+                    );
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+                Ok(resp) => {
+                    eyre::bail!("Claude API returned {}: {}", resp.status(), resp.text().await.unwrap_or_default())
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if response.is_null() {
+            return Err(FuzzerError::RetryError(format!("{body:?}"), config::RETRY_N).into());
+        }
+
+        let text = response["content"]
+            .as_array()
+            .and_then(|blocks| blocks.iter().find(|b| b["type"] == "text"))
+            .and_then(|b| b["text"].as_str())
+            .ok_or_else(|| eyre::eyre!("Claude response had no text content block: {response}"))?
+            .to_string();
+        let input_tokens = response["usage"]["input_tokens"].as_u64().unwrap_or(0) as u32;
+        let output_tokens = response["usage"]["output_tokens"].as_u64().unwrap_or(0) as u32;
+        let usage = TokenUsage::new(input_tokens, output_tokens, input_tokens + output_tokens);
+        Ok((text, usage))
+    }
+}
+
+impl Handler for ClaudeHandler {
+    fn generate(&self, prompt: &super::prompt::Prompt) -> eyre::Result<Vec<Program>> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let chat_msgs = prompt.to_chatgpt_message();
+        let mut futures = Vec::new();
+        for _ in 0..config::get_config().n_sample {
+            futures.push(self.messages(&chat_msgs));
+        }
+        let results = rt.block_on(join_all(futures));
+
+        let mut programs = Vec::new();
+        let mut total_usage = TokenUsage::default();
+        for result in results {
+            let (text, usage) = result?;
+            total_usage.add(&usage);
+            programs.push(Program::new(&strip_code_wrapper(&text)));
+        }
+        log::info!(
+            "Claude Token Usage - Prompt: {}, Completion: {}, Total: {}",
+            total_usage.prompt_tokens,
+            total_usage.completion_tokens,
+            total_usage.total_tokens
+        );
+        super::usage::record(&config::get_openai_model_name(), &total_usage)?;
+        Ok(programs)
+    }
+
+    fn generate_json(&self, prompt: String, deopt: &Deopt) -> eyre::Result<serde_json::Value> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let context = super::openai::gather_header_context(deopt, &prompt);
+        let prompt = format!("{prompt}\n{context}");
+        let user_msg: ChatCompletionRequestMessage = ChatCompletionRequestUserMessageArgs::default()
+            .content(prompt)
+            .build()?
+            .into();
+        let (text, usage) = rt.block_on(self.messages(&[user_msg]))?;
+        super::usage::record(&config::get_openai_model_name(), &usage)?;
+        Ok(serde_json::from_str(&strip_json_code_fence(&text))?)
+    }
+}
@@ -0,0 +1,131 @@
+use crate::config;
+
+/// Estimates how many tokens a piece of text will cost once sent to the model. The default
+/// `HeuristicTokenizer` trades accuracy for zero dependencies; a project that needs exact counts
+/// for a specific model can plug one in (e.g. backed by `tiktoken-rs`) by implementing this trait.
+pub trait Tokenizer {
+    fn count_tokens(&self, text: &str) -> usize;
+}
+
+/// Approximates token count as `bytes / 4`, the commonly cited rule of thumb for English/code
+/// text under BPE-style tokenizers. Good enough to keep prompts under budget without pulling in a
+/// model-specific tokenizer.
+pub struct HeuristicTokenizer;
+
+impl Tokenizer for HeuristicTokenizer {
+    fn count_tokens(&self, text: &str) -> usize {
+        text.len().div_ceil(4)
+    }
+}
+
+pub fn default_tokenizer() -> &'static dyn Tokenizer {
+    &HeuristicTokenizer
+}
+
+/// One candidate entry -- a system header, an exported API signature, or a custom type
+/// declaration -- considered for inclusion in the `{headers}`/`{APIs}`/`{context}` sections of
+/// `config::SYSTEM_CONTEXT_TEMPLATE`.
+pub struct ContextEntry {
+    /// The identifier this entry declares (header path, API/function name, or type name), used
+    /// to check it against `force_types` and the current `{combinations}` text.
+    pub name: String,
+    /// The exact text spliced into the template section.
+    pub text: String,
+}
+
+/// Greedily drop the least-relevant entries from `entries` until `fixed_tokens` (the rest of the
+/// rendered system+user prompt) plus the survivors' token count plus `config::MAX_TOKENS` fits
+/// under `config::get_openai_context_limit()`. Entries whose name is in `force_types` or appears
+/// in the current `{combinations}` text are protected and never dropped, since those are the ones
+/// the generated driver is actually expected to use; if the budget is still exceeded once every
+/// unprotected entry is gone, the rest is returned as-is with a warning. Unprotected entries are
+/// dropped from the end of `entries` first, so callers should order it most-relevant-first. Returns the surviving
+/// entries; anything dropped is logged by name so results stay interpretable.
+///
+/// When `OPENAI_CONTEXT_LIMIT` wasn't set, there is no budget to enforce and `entries` is
+/// returned unchanged.
+pub fn trim_to_budget(
+    entries: Vec<ContextEntry>,
+    force_types: &[String],
+    combinations: &str,
+    fixed_tokens: usize,
+    tokenizer: &dyn Tokenizer,
+) -> Vec<ContextEntry> {
+    let Some(limit) = *config::get_openai_context_limit() else {
+        return entries;
+    };
+    let budget = (limit as usize).saturating_sub(config::MAX_TOKENS as usize);
+
+    let is_protected = |entry: &ContextEntry| {
+        force_types.iter().any(|t| t == &entry.name) || combinations.contains(&entry.name)
+    };
+
+    let mut kept = entries;
+    let mut dropped: Vec<String> = Vec::new();
+
+    loop {
+        let total: usize =
+            fixed_tokens + kept.iter().map(|e| tokenizer.count_tokens(&e.text)).sum::<usize>();
+        if total <= budget {
+            break;
+        }
+        match kept.iter().rposition(|e| !is_protected(e)) {
+            Some(idx) => dropped.push(kept.remove(idx).name),
+            None => {
+                log::warn!(
+                    "Context still exceeds the {budget} token budget after dropping every droppable entry; sending as-is"
+                );
+                break;
+            }
+        }
+    }
+
+    if !dropped.is_empty() {
+        log::info!(
+            "Dropped {} context entries to stay under the {budget} token budget: {}",
+            dropped.len(),
+            dropped.join(", ")
+        );
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ByteTokenizer;
+    impl Tokenizer for ByteTokenizer {
+        fn count_tokens(&self, text: &str) -> usize {
+            text.len()
+        }
+    }
+
+    fn entry(name: &str, text: &str) -> ContextEntry {
+        ContextEntry { name: name.to_string(), text: text.to_string() }
+    }
+
+    // `OPENAI_CONTEXT_LIMIT` is a process-global `OnceCell` set at most once, so this test can't
+    // pin its own value if some other test in the binary already set one first; it bails out
+    // rather than asserting against whatever value won the race.
+    #[test]
+    fn test_trim_to_budget_drops_unprotected_entries_but_protects_force_types() {
+        // MAX_TOKENS (2048) alone exceeds this, so the budget saturates to 0: any unprotected
+        // entry must be dropped, while a force_types-protected entry survives regardless.
+        let _ = config::OPENAI_CONTEXT_LIMIT.set(Some(100));
+        let Some(limit) = *config::get_openai_context_limit() else {
+            return;
+        };
+        let budget = (limit as usize).saturating_sub(config::MAX_TOKENS as usize);
+        if budget != 0 {
+            return;
+        }
+
+        let entries = vec![entry("keep_me", "protected"), entry("drop_me", "y")];
+        let kept = trim_to_budget(entries, &["keep_me".to_string()], "", 0, &ByteTokenizer);
+
+        let names: Vec<&str> = kept.iter().map(|e| e.name.as_str()).collect();
+        assert!(names.contains(&"keep_me"), "force_types-protected entries must survive");
+        assert!(!names.contains(&"drop_me"), "unprotected entries must be dropped to fit");
+    }
+}
@@ -0,0 +1,211 @@
+use std::path::PathBuf;
+
+use crate::{config::{self, ClientConfig}, deopt::Deopt, program::Program};
+use async_openai::types::ChatCompletionRequestMessage;
+use eyre::Result;
+use llama_cpp_2::{
+    context::params::LlamaContextParams,
+    llama_backend::LlamaBackend,
+    llama_batch::LlamaBatch,
+    model::{params::LlamaModelParams, AddBos, LlamaModel},
+    sampling::LlamaSampler,
+};
+use serde_json::Value;
+
+use super::openai::{strip_code_wrapper, structured_output_schema, TokenUsage};
+
+/// `Handler` that serves `generate`/`generate_json` from a local GGUF model via `llama.cpp`,
+/// for fuzzing runs on machines that can't or shouldn't reach a hosted API. `n_sample` is
+/// honored by running that many independent decodes rather than one multi-choice request, since
+/// llama.cpp's sampling API decodes one sequence at a time.
+pub struct LlamaCppHandler {
+    backend: LlamaBackend,
+    model: LlamaModel,
+    chat_template: String,
+}
+
+impl LlamaCppHandler {
+    pub fn new(config: &ClientConfig) -> Result<Self> {
+        let ClientConfig::LlamaCpp { model_path, chat_template, .. } = config else {
+            eyre::bail!("LlamaCppHandler constructed from a non-llama-cpp client config");
+        };
+        let backend = LlamaBackend::init()?;
+        let model_params = LlamaModelParams::default();
+        let model = LlamaModel::load_from_file(&backend, PathBuf::from(model_path), &model_params)?;
+        let chat_template = chat_template
+            .clone()
+            .or_else(|| model.chat_template(None).ok())
+            .ok_or_else(|| eyre::eyre!("{model_path} has no baked-in chat template and none was configured"))?;
+        Ok(Self { backend, model, chat_template })
+    }
+
+    /// Build the sampler `decode` draws tokens from. A `grammar` always wins, since it's used to
+    /// constrain `generate_json`'s structured extraction rather than to sample varied programs.
+    /// Otherwise chain temperature + top-p + a freshly-seeded distribution on
+    /// `config::get_config().temperature`, so repeated `decode` calls (how `generate` honors
+    /// `n_sample`, since llama.cpp samples one sequence at a time) actually produce independent
+    /// completions instead of `n_sample` copies of the same greedy decode. A temperature of 0
+    /// still means argmax sampling, matching the OpenAI-family backends.
+    fn build_sampler(&self, grammar: Option<&str>) -> Result<LlamaSampler> {
+        if let Some(gbnf) = grammar {
+            return Ok(LlamaSampler::grammar(&self.model, gbnf, "root")?);
+        }
+        let temperature = config::get_config().temperature;
+        if temperature <= 0.0 {
+            return Ok(LlamaSampler::greedy());
+        }
+        Ok(LlamaSampler::chain_simple([
+            LlamaSampler::top_p(0.95, 1),
+            LlamaSampler::temp(temperature),
+            LlamaSampler::dist(sampler_seed()),
+        ]))
+    }
+
+    /// Decode a prompt already rendered by `render_prompt`, optionally constraining decoding with
+    /// a GBNF grammar.
+    fn decode(&self, prompt: &str, grammar: Option<&str>) -> Result<(String, TokenUsage)> {
+        let ctx_params = LlamaContextParams::default();
+        let mut ctx = self.model.new_context(&self.backend, ctx_params)?;
+
+        let tokens = self.model.str_to_token(prompt, AddBos::Always)?;
+        let prompt_tokens = tokens.len() as u32;
+
+        let mut batch = LlamaBatch::new(512, 1);
+        for (i, token) in tokens.iter().enumerate() {
+            batch.add(*token, i as i32, &[0], i == tokens.len() - 1)?;
+        }
+        ctx.decode(&mut batch)?;
+
+        let mut sampler = self.build_sampler(grammar)?;
+
+        let mut output = String::new();
+        let mut completion_tokens = 0u32;
+        let mut n_cur = batch.n_tokens();
+        loop {
+            let token = sampler.sample(&ctx, batch.n_tokens() - 1);
+            if self.model.is_eog_token(token) || completion_tokens >= config::MAX_TOKENS as u32 {
+                break;
+            }
+            output.push_str(&self.model.token_to_str(token)?);
+            completion_tokens += 1;
+
+            batch.clear();
+            batch.add(token, n_cur, &[0], true)?;
+            ctx.decode(&mut batch)?;
+            n_cur += 1;
+        }
+
+        let usage = TokenUsage::new(prompt_tokens, completion_tokens, prompt_tokens + completion_tokens);
+        Ok((output, usage))
+    }
+
+    fn render_prompt(&self, msgs: &[ChatCompletionRequestMessage]) -> Result<String> {
+        let mut env = minijinja::Environment::new();
+        env.add_template("chat", &self.chat_template)?;
+        let messages: Vec<Value> = msgs
+            .iter()
+            .map(|m| serde_json::to_value(m).unwrap_or_default())
+            .collect();
+        let tmpl = env.get_template("chat")?;
+        Ok(tmpl.render(minijinja::context! { messages => messages, add_generation_prompt => true })?)
+    }
+}
+
+impl super::Handler for LlamaCppHandler {
+    fn generate(&self, prompt: &super::prompt::Prompt) -> eyre::Result<Vec<Program>> {
+        let chat_msgs = prompt.to_chatgpt_message();
+        let rendered = self.render_prompt(&chat_msgs)?;
+
+        let mut programs = Vec::new();
+        let mut total_usage = TokenUsage::default();
+        // llama.cpp decodes one sequence at a time, so `n_sample` is honored as repeated decodes
+        // rather than a single batched request.
+        for _ in 0..config::get_config().n_sample {
+            let (text, usage) = self.decode(&rendered, None)?;
+            total_usage.add(&usage);
+            programs.push(Program::new(&strip_code_wrapper(&text)));
+        }
+        log::info!(
+            "llama.cpp Token Usage - Prompt: {}, Completion: {}, Total: {}",
+            total_usage.prompt_tokens,
+            total_usage.completion_tokens,
+            total_usage.total_tokens
+        );
+        super::usage::record(&config::get_openai_model_name(), &total_usage)?;
+        Ok(programs)
+    }
+
+    fn generate_json(&self, prompt: String, _deopt: &Deopt) -> eyre::Result<serde_json::Value> {
+        let user_msg: ChatCompletionRequestMessage =
+            async_openai::types::ChatCompletionRequestUserMessageArgs::default()
+                .content(prompt)
+                .build()?
+                .into();
+        let rendered = self.render_prompt(&[user_msg])?;
+
+        // Constrain decoding to the same ownership-info JSON schema the OpenAI-family backends
+        // ask for via `response_format`, so the output is guaranteed well-formed JSON.
+        let grammar = json_schema_to_gbnf(&structured_output_schema());
+        let (text, usage) = self.decode(&rendered, Some(&grammar))?;
+        super::usage::record(&config::get_openai_model_name(), &usage)?;
+        Ok(serde_json::from_str(text.trim())?)
+    }
+}
+
+/// A fresh seed for the distribution sampler, so repeated `decode` calls within one `generate`
+/// don't draw the same token sequence from an identical RNG state.
+fn sampler_seed() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0)
+}
+
+/// Compile a (subset of) JSON Schema down to a GBNF grammar so llama.cpp's grammar-constrained
+/// sampling can guarantee well-formed output for the `{APIs, library_boilerplate}` shape. Covers
+/// just the constructs `structured_output_schema` actually uses: objects, arrays, strings, and
+/// string enums.
+fn json_schema_to_gbnf(schema: &Value) -> String {
+    fn node_rule(name: &str, schema: &Value, rules: &mut Vec<String>) -> String {
+        match schema.get("enum") {
+            Some(Value::Array(variants)) => {
+                let alts: Vec<String> = variants
+                    .iter()
+                    .filter_map(|v| v.as_str())
+                    .map(|s| format!("\"\\\"{s}\\\"\""))
+                    .collect();
+                let rule = format!("{name} ::= ( {} )", alts.join(" | "));
+                rules.push(rule);
+                name.to_string()
+            }
+            _ => match schema.get("type").and_then(Value::as_str) {
+                Some("object") => {
+                    let props = schema["properties"].as_object().cloned().unwrap_or_default();
+                    let mut fields = Vec::new();
+                    for (key, prop_schema) in &props {
+                        let field_rule = node_rule(&format!("{name}-{key}"), prop_schema, rules);
+                        fields.push(format!("\"\\\"{key}\\\":\" {field_rule}"));
+                    }
+                    let rule = format!("{name} ::= \"{{\" {} \"}}\"", fields.join(" \",\" "));
+                    rules.push(rule);
+                    name.to_string()
+                }
+                Some("array") => {
+                    let item_rule = node_rule(&format!("{name}-item"), &schema["items"], rules);
+                    let rule =
+                        format!("{name} ::= \"[\" ( {item_rule} (\",\" {item_rule})* )? \"]\"");
+                    rules.push(rule);
+                    name.to_string()
+                }
+                _ => "string".to_string(),
+            },
+        }
+    }
+
+    let mut rules = vec!["string ::= \"\\\"\" [^\"]* \"\\\"\"".to_string()];
+    let root = node_rule("root", schema, &mut rules);
+    if root != "root" {
+        rules.push(format!("root ::= {root}"));
+    }
+    rules.join("\n")
+}
@@ -1,20 +1,23 @@
-use std::{process::Child, time::Duration};
+use std::{collections::{HashMap, HashSet}, process::Child, sync::RwLock, time::Duration};
 use crate::{
     config::{self, get_config, get_openai_proxy},
     is_critical_err,
     program::Program,
     FuzzerError,
-    deopt::Deopt, 
+    deopt::Deopt,
     analysis::header as headers,
 };
 use async_openai::{
     config::OpenAIConfig, types::{
-        ChatCompletionRequestMessage, CreateChatCompletionRequest, CreateChatCompletionRequestArgs, CreateChatCompletionResponse,  ResponseFormatJsonSchema,ResponseFormat, ChatCompletionRequestUserMessageArgs,
+        ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, ChatCompletionRequestToolMessageArgs,
+        ChatCompletionRequestUserMessageArgs, ChatCompletionStreamOptions, ChatCompletionTool, ChatCompletionToolType,
+        CreateChatCompletionRequest, CreateChatCompletionRequestArgs, CreateChatCompletionResponse, FinishReason,
+        FunctionObject, ResponseFormat, ResponseFormatJsonSchema,
     }, Client
 };
 use eyre::Result;
 use once_cell::sync::OnceCell;
-use futures::future::join_all;
+use futures::{future::join_all, StreamExt};
 
 use serde_json::{json, to_value, Value};
 
@@ -87,7 +90,7 @@ impl Handler for OpenAIHanler {
 	
         let mut futures = Vec::new();
         for _ in 0..get_config().n_sample {
-            let future = generate_program_by_chat(chat_msgs.clone());
+            let future = generate_program_by_chat_stream(chat_msgs.clone());
             futures.push(future);
         }
         let results = self.rt.block_on(join_all(futures));
@@ -112,50 +115,56 @@ impl Handler for OpenAIHanler {
     }
     
     fn generate_json(&self, prompt: String, deopt: &Deopt) -> eyre::Result<serde_json::Value> {
-        let mut files = headers::get_include_sys_headers(deopt).clone();
-        files.extend(headers::get_include_lib_headers(deopt)?);
-        
-        let mut allfiles = Vec::new();
-    	for header in &files {
-        	let path = headers::resolve_lib_header(deopt, header)?;
-        	allfiles.push(path.to_string_lossy().to_string());
-    	}
-    	
-    	//add document
-    	let docs_path = deopt.get_library_build_dir()?;
-    	for candidate in &["README.md", "README.txt", "README"] {
-    		let path = docs_path.join(candidate);
-    		if path.exists() {
-        		allfiles.push(path.to_string_lossy().to_string());
-        	}
-    	}	
-        
         self.rt.block_on(async {
-        	let (json, _usage) = generate_json_by_chat(prompt,Some(allfiles)).await?;
-        	Ok(json)
-    	})
+            let (json, _usage) = generate_json_with_tools(prompt, deopt).await?;
+            Ok(json)
+        })
     }
 }
 
-/// Get the OpenAI interface client.
-fn get_client() -> Result<&'static Client<OpenAIConfig>> {
-    // read OpenAI API key form the env var (OPENAI_API_KEY).
-    pub static CLIENT: OnceCell<Client<OpenAIConfig>> = OnceCell::new();
-    let client = CLIENT.get_or_init(|| {
-        let http_client = reqwest::ClientBuilder::new()
-            .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(180))
-            .build()
-            .unwrap();
-        let openai_config = if let Some(proxy) = get_openai_proxy() {
-            OpenAIConfig::default().with_api_base(proxy)
-        } else {
-            OpenAIConfig::new()
-        };
-        let client = Client::with_config(openai_config);
-        let client = client.with_http_client(http_client);
-        client
-    });
+/// Get the OpenAI interface client for the currently configured model, honoring its `clients.yaml`
+/// `extra` overrides (proxy, timeouts, organization, headers) if one is set.
+///
+/// Clients are memoized keyed on the resolved config rather than a single global slot, so
+/// switching models/endpoints at runtime (a different `clients.yaml` entry matching the new
+/// model name) builds a fresh client instead of reusing a stale connection pinned to the old
+/// proxy/timeouts.
+fn get_client() -> Result<Client<OpenAIConfig>> {
+    static CLIENTS: OnceCell<RwLock<HashMap<String, Client<OpenAIConfig>>>> = OnceCell::new();
+    let clients = CLIENTS.get_or_init(|| RwLock::new(HashMap::new()));
+
+    let model = config::get_openai_model_name();
+    let client_config = config::get_client_config_for_model(&model);
+    let key = match client_config {
+        Some(cfg) => format!("{cfg:?}"),
+        None => format!("default:{:?}", get_openai_proxy()),
+    };
+
+    if let Some(client) = clients.read().unwrap().get(&key) {
+        return Ok(client.clone());
+    }
+
+    let (api_key, api_base, organization_id, extra) = match client_config {
+        Some(config::ClientConfig::OpenAI { api_key, api_base, organization_id, extra, .. }) => {
+            (api_key.clone(), api_base.clone(), organization_id.clone(), extra.as_ref())
+        }
+        Some(_) | None => (None, None, None, client_config.and_then(|c| c.extra())),
+    };
+
+    let http_client = config::build_http_client(extra)?;
+    let mut openai_config = match api_base.or_else(|| get_openai_proxy().clone()) {
+        Some(api_base) => OpenAIConfig::new().with_api_base(api_base),
+        None => OpenAIConfig::new(),
+    };
+    if let Some(api_key) = api_key {
+        openai_config = openai_config.with_api_key(api_key);
+    }
+    if let Some(org) = organization_id.or_else(|| extra.and_then(|e| e.organization_id.clone())) {
+        openai_config = openai_config.with_org_id(org);
+    }
+    let client = Client::with_config(openai_config).with_http_client(http_client);
+
+    clients.write().unwrap().insert(key, client.clone());
     Ok(client)
 }
 
@@ -177,15 +186,11 @@ fn create_chat_request(
     Ok(request)
 }
 
-fn create_structured_request(
-    msg: String,
-    stop: Option<String>,
-    files: Option<Vec<String>>,
-) -> Result<CreateChatCompletionRequest> {
-    let mut binding = CreateChatCompletionRequestArgs::default();
-    let binding = binding.model(config::get_openai_model_name());
-
-    let schema = json!({
+/// JSON schema of the structured `{APIs, library_boilerplate}` extraction output. Shared across
+/// backends: OpenAI-family handlers pass it as a `response_format` schema, while the llama.cpp
+/// backend compiles it down to a GBNF grammar to constrain decoding the same way.
+pub(crate) fn structured_output_schema() -> Value {
+    json!({
 	    "type": "object",
 	    "properties": {
 		"APIs": {
@@ -195,18 +200,18 @@ fn create_structured_request(
 		        "type": "object",
 		        "properties": {
 		            "name": { "type": "string", "description": "Function name of the API" },
-		            "arg_ownership_info": { 
+		            "arg_ownership_info": {
 		                "type": "array",
 		                "description": "Information about responsibility of freeing, if caller keeps ownership or not.",
-		                "items": { 
-		                    "enum": ["Caller keeps ownership", "Caller loses ownership", "None"], 
-		                    "type": "string" 
+		                "items": {
+		                    "enum": ["Caller keeps ownership", "Caller loses ownership", "None"],
+		                    "type": "string"
 		                }
 		            },
-		            "ret_ownership_info": { 
-		                "enum": ["Caller owns", "Library owns", "None"], 
-		                "type": "string", 
-		                "description": "Information about responsibility of freeing, if caller has ownership or not." 
+		            "ret_ownership_info": {
+		                "enum": ["Caller owns", "Library owns", "None"],
+		                "type": "string",
+		                "description": "Information about responsibility of freeing, if caller has ownership or not."
 		            },
 		            "func_info": { "type": "string", "description": "Other useful information for fuzzing harness generation (ex: must-follow how-to-use, other function which should be called before this function, etc)" }
 		        },
@@ -221,73 +226,150 @@ fn create_structured_request(
 	    },
 	    "required": ["APIs", "library_boilerplate"],
 	    "additionalProperties": false
-    });
-    
-    let mut full_msg = msg;
-    if let Some(paths) = files {
-        let mut header_files = Vec::new();
-        let mut doc_files = Vec::new();
-        
-        for path in &paths {
-        	if path.to_lowercase().contains("readme") {
-            		doc_files.push(path);
-        	} else {
-            		header_files.push(path);
-        	}
-    	}
-	for path in &header_files {
-	    match std::fs::read_to_string(path) {
-		Ok(text) => {
-		    full_msg.push_str(&format!("\n--- Header File ---\n"));
-		    full_msg.push_str(&text);
-		}
-		Err(e) => log::warn!("Could not read {}: {}", path, e),
-	    }
-	}
+    })
+}
 
-	for path in &doc_files {
-	    match std::fs::read_to_string(path) {
-		Ok(text) => {
-		    full_msg.push_str(&format!("\n--- Documentation File  ---\n"));
-		    full_msg.push_str(&text);
-		}
-		Err(e) => log::warn!("Could not read {}: {}", path, e),
-	    }
-	}
-    }
-    
-    let user_msg = ChatCompletionRequestUserMessageArgs::default()
-        .content(full_msg)
-        .build()?
-        .into();
-    
-    let mut request = binding
-        .messages(vec![user_msg])
-        .temperature(config::get_config().temperature)
-        .response_format(ResponseFormat::JsonSchema {
-            json_schema: ResponseFormatJsonSchema {
-                schema: Some(schema),
-                description: Some("Extract structured API info for fuzzing harness".into()),
-                name: "fuzzing_harness_gen".into(),
-                strict: Some(true),
+/// Create a streaming variant of `create_chat_request`, asking the server to include a final
+/// usage chunk so `TokenUsage` can be built from streamed deltas rather than a single response.
+fn create_streaming_chat_request(
+    msgs: Vec<ChatCompletionRequestMessage>,
+    stop: Option<String>,
+) -> Result<CreateChatCompletionRequest> {
+    let mut request = create_chat_request(msgs, stop)?;
+    request.stream = Some(true);
+    request.stream_options = Some(ChatCompletionStreamOptions { include_usage: true });
+    Ok(request)
+}
+
+/// Outcome of consuming a streamed chat completion. `content` and `usage` reflect everything
+/// received so far even when `error` is set, so a mid-stream failure doesn't throw away partial
+/// output the caller could still use.
+struct StreamOutcome {
+    content: String,
+    usage: TokenUsage,
+    finish_reason: Option<FinishReason>,
+    error: Option<eyre::Report>,
+}
+
+/// Consume a streaming chat completion, reassembling the content incrementally and invoking
+/// `on_delta` as each chunk arrives so callers can log progress. Stops on a stream error rather
+/// than propagating it, so partial content is never silently discarded.
+async fn get_chat_response_stream(
+    request: CreateChatCompletionRequest,
+    mut on_delta: impl FnMut(&str),
+) -> Result<StreamOutcome> {
+    let client = get_client()?;
+    let mut stream = client.chat().create_stream(request).await?;
+
+    let mut content = String::new();
+    let mut usage = TokenUsage::default();
+    let mut finish_reason = None;
+    let mut error = None;
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(response) => {
+                if let Some(choice) = response.choices.first() {
+                    if let Some(delta) = &choice.delta.content {
+                        content.push_str(delta);
+                        on_delta(delta);
+                    }
+                    if let Some(reason) = choice.finish_reason {
+                        finish_reason = Some(reason);
+                    }
+                }
+                if let Some(u) = &response.usage {
+                    usage = TokenUsage::new(u.prompt_tokens, u.completion_tokens, u.total_tokens);
+                }
             }
-        });
-    
-    if let Some(stop) = stop {
-        request = request.stop(stop);
+            Err(e) => {
+                error = Some(eyre::Report::new(e));
+                break;
+            }
+        }
     }
-    let request = request.build()?;
+    Ok(StreamOutcome {
+        content,
+        usage,
+        finish_reason,
+        error,
+    })
+}
 
-    Ok(request)
+/// Sample one program from a streamed chat completion, logging each delta at trace level as it
+/// arrives. No idle timeout is applied to the stream: a stalled stream ends only when the HTTP
+/// client's request timeout fires, and truncation is seen once the stream ends. A `finish_reason`
+/// of `length` is treated as a retriable truncation rather than a silent success, a stream that
+/// fails partway through (`StreamOutcome::error`) is retried rather than returned as a success
+/// built from whatever partial content arrived before the drop, and a failure to even establish
+/// the stream (network blip, 429, transient 5xx) gets the same `backoff_delay`/`is_critical_err`
+/// treatment as `get_chat_response`, rather than propagating straight out of the retry loop.
+pub async fn generate_program_by_chat_stream(
+    chat_msgs: Vec<ChatCompletionRequestMessage>,
+) -> Result<(Program, TokenUsage)> {
+    for attempt in 0..config::RETRY_N {
+        let request = create_streaming_chat_request(chat_msgs.clone(), None)?;
+        let result = get_chat_response_stream(request, |delta| log::trace!("stream delta: {delta}")).await;
+        let outcome = match is_critical_err(&result) {
+            crate::Critical::Normal => result?,
+            crate::Critical::NonCritical => {
+                let delay = backoff_delay(attempt);
+                log::warn!(
+                    "Failed to establish chat stream (attempt {}/{}), backing off {:.1}s before retry: {}",
+                    attempt + 1,
+                    config::RETRY_N,
+                    delay.as_secs_f32(),
+                    result.as_ref().err().map(ToString::to_string).unwrap_or_default(),
+                );
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+            crate::Critical::Critical => return Err(result.err().unwrap()),
+        };
+
+        if outcome.finish_reason == Some(FinishReason::Length) {
+            log::warn!("Stream attempt {attempt} was truncated at max_tokens, retrying");
+            super::usage::record(&config::get_openai_model_name(), &outcome.usage)?;
+            continue;
+        }
+        if let Some(e) = &outcome.error {
+            let delay = backoff_delay(attempt);
+            log::warn!(
+                "Stream attempt {attempt} failed mid-stream, backing off {:.1}s before retry: {e}",
+                delay.as_secs_f32()
+            );
+            super::usage::record(&config::get_openai_model_name(), &outcome.usage)?;
+            tokio::time::sleep(delay).await;
+            continue;
+        }
+
+        super::usage::record(&config::get_openai_model_name(), &outcome.usage)?;
+        let content = strip_code_wrapper(&outcome.content);
+        return Ok((Program::new(&content), outcome.usage));
+    }
+    Err(FuzzerError::RetryError("streamed chat completion".to_string(), config::RETRY_N).into())
 }
 
+/// Exponential-backoff-with-jitter delay before retry `attempt` (0-indexed), capped at 30s.
+/// async_openai surfaces errors as parsed JSON rather than raw HTTP responses, so there's no
+/// reliable header to read a `Retry-After` from; this is deliberately conservative so a long
+/// fuzzing campaign backs off hard on sustained 429s/5xxs instead of hammering the endpoint.
+pub(crate) fn backoff_delay(attempt: u8) -> Duration {
+    let base_ms = 500u64.saturating_mul(1u64 << attempt.min(6));
+    let capped_ms = base_ms.min(30_000);
+    let jitter_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_millis() as u64 % (capped_ms / 2 + 1))
+        .unwrap_or(0);
+    Duration::from_millis(capped_ms / 2 + jitter_ms)
+}
 
-/// Get a response for a chat request
+/// Get a response for a chat request, backing off between retries so a 429 or transient 5xx
+/// doesn't burn through `RETRY_N` attempts back-to-back.
 async fn get_chat_response(
     request: CreateChatCompletionRequest,
 ) -> Result<CreateChatCompletionResponse> {
-    let client = get_client().unwrap();
-    for _retry in 0..config::RETRY_N {
+    let client = get_client()?;
+    for retry in 0..config::RETRY_N {
         let response = client
             .chat()
             .create(request.clone())
@@ -299,6 +381,15 @@ async fn get_chat_response(
                 return Ok(response);
             }
             crate::Critical::NonCritical => {
+                let delay = backoff_delay(retry);
+                log::warn!(
+                    "Chat request failed (attempt {}/{}), backing off {:.1}s before retry: {}",
+                    retry + 1,
+                    config::RETRY_N,
+                    delay.as_secs_f32(),
+                    response.as_ref().err().map(ToString::to_string).unwrap_or_default(),
+                );
+                tokio::time::sleep(delay).await;
                 continue;
             }
             crate::Critical::Critical => return Err(response.err().unwrap()),
@@ -307,36 +398,229 @@ async fn get_chat_response(
     Err(FuzzerError::RetryError(format!("{request:?}"), config::RETRY_N).into())
 }
 
-pub async fn generate_json_by_chat(
+const MAX_TOOL_STEPS: u8 = 8;
+
+fn header_tool_defs() -> Vec<ChatCompletionTool> {
+    let list_headers = FunctionObject {
+        name: "list_headers".into(),
+        description: Some("List the system and library header files available for this library.".into()),
+        parameters: Some(json!({"type": "object", "properties": {}, "additionalProperties": false})),
+        strict: None,
+    };
+    let read_header = FunctionObject {
+        name: "read_header".into(),
+        description: Some("Read the contents of one header file returned by list_headers.".into()),
+        parameters: Some(json!({
+            "type": "object",
+            "properties": { "path": { "type": "string", "description": "A header path as returned by list_headers" } },
+            "required": ["path"],
+            "additionalProperties": false
+        })),
+        strict: None,
+    };
+    let read_docs = FunctionObject {
+        name: "read_docs".into(),
+        description: Some("Read the library's README, if one exists.".into()),
+        parameters: Some(json!({"type": "object", "properties": {}, "additionalProperties": false})),
+        strict: None,
+    };
+    [list_headers, read_header, read_docs]
+        .into_iter()
+        .map(|function| ChatCompletionTool {
+            r#type: ChatCompletionToolType::Function,
+            function,
+        })
+        .collect()
+}
+
+/// Execute one of the tools the model requested, against `deopt`'s include paths.
+fn call_header_tool(name: &str, args: &str, deopt: &Deopt) -> Result<String> {
+    match name {
+        "list_headers" => {
+            let mut files = headers::get_include_sys_headers(deopt).clone();
+            files.extend(headers::get_include_lib_headers(deopt)?);
+            Ok(serde_json::to_string(&files)?)
+        }
+        "read_header" => {
+            let args: Value = serde_json::from_str(args).unwrap_or_default();
+            let path = args["path"]
+                .as_str()
+                .ok_or_else(|| eyre::eyre!("read_header called without a `path` argument"))?;
+            let resolved = headers::resolve_lib_header(deopt, path)?;
+            Ok(std::fs::read_to_string(resolved)?)
+        }
+        "read_docs" => {
+            let docs_path = deopt.get_library_build_dir()?;
+            for candidate in &["README.md", "README.txt", "README"] {
+                let path = docs_path.join(candidate);
+                if path.exists() {
+                    return Ok(std::fs::read_to_string(path)?);
+                }
+            }
+            Ok("No README found for this library.".to_string())
+        }
+        other => eyre::bail!("Unknown tool requested by the model: {other}"),
+    }
+}
+
+/// Drive `generate_json`'s structured-extraction chat with a bounded tool-calling loop: rather
+/// than stuffing every header and the README into one prompt (which blows the context window on
+/// large libraries), the model starts with just the task prompt and pulls in `list_headers`,
+/// `read_header`, and `read_docs` results on demand. Capped at `MAX_TOOL_STEPS` round-trips so a
+/// model that never stops calling tools can't loop forever.
+pub async fn generate_json_with_tools(
     prompt: String,
-    files: Option<Vec<String>>,
+    deopt: &Deopt,
 ) -> Result<(serde_json::Value, TokenUsage)> {
-    
-    let request = create_structured_request(prompt, None, files)?;
-    let respond = get_chat_response(request).await?;
-    
-    let usage = TokenUsage::from_response(&respond);
-    let choice = respond.choices.first().unwrap();
-    let content = choice.message.content.as_ref().unwrap();
-    let parsed: serde_json::Value = serde_json::from_str(content)?;
-    Ok((parsed, usage))
-}
+    let tools = header_tool_defs();
+    let mut messages: Vec<ChatCompletionRequestMessage> = vec![ChatCompletionRequestUserMessageArgs::default()
+        .content(prompt)
+        .build()?
+        .into()];
 
-pub async fn generate_program_by_chat(
-    chat_msgs: Vec<ChatCompletionRequestMessage>,
-) -> Result<(Program, TokenUsage)> {
+    let mut total_usage = TokenUsage::default();
+    for _step in 0..MAX_TOOL_STEPS {
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(config::get_openai_model_name())
+            .messages(messages.clone())
+            .temperature(config::get_config().temperature)
+            .tools(tools.clone())
+            .response_format(ResponseFormat::JsonSchema {
+                json_schema: ResponseFormatJsonSchema {
+                    schema: Some(structured_output_schema()),
+                    description: Some("Extract structured API info for fuzzing harness".into()),
+                    name: "fuzzing_harness_gen".into(),
+                    strict: Some(true),
+                },
+            })
+            .build()?;
+        let response = get_chat_response(request).await?;
+        total_usage.add(&TokenUsage::from_response(&response));
 
-    let request = create_chat_request(chat_msgs, None)?;
-    let respond = get_chat_response(request).await?;
-    
-    let usage = TokenUsage::from_response(&respond);
-    let choice = respond.choices.first().unwrap();
-    let content = choice.message.content.as_ref().unwrap();
-    let content = strip_code_wrapper(&content);
-    let program = Program::new(&content);
-    Ok((program, usage))
+        let choice = response.choices.first().unwrap();
+        let message = &choice.message;
+
+        if let Some(tool_calls) = &message.tool_calls {
+            if !tool_calls.is_empty() {
+                messages.push(
+                    ChatCompletionRequestAssistantMessageArgs::default()
+                        .tool_calls(tool_calls.clone())
+                        .build()?
+                        .into(),
+                );
+                let results: HashMap<String, String> = tool_calls
+                    .iter()
+                    .map(|call| {
+                        let result = call_header_tool(&call.function.name, &call.function.arguments, deopt)
+                            .unwrap_or_else(|e| format!("Error: {e}"));
+                        (call.id.clone(), result)
+                    })
+                    .collect();
+
+                // Tool results (raw header/README contents) are exactly the kind of unboundedly
+                // sized text `OPENAI_CONTEXT_LIMIT` is meant to guard against, so run them through
+                // the same budget the rest of the prompt is held to rather than appending them
+                // unconditionally. `deopt.config.force_types` is passed through as in the eager
+                // `gather_header_context` fallback below, but these entries are named by tool call
+                // id, so it matches none of them and any tool result can be dropped. There's no
+                // `{combinations}` text at this stage since that's assembled later by
+                // `request::prompt` from the APIs this call extracts.
+                let tokenizer = super::context_budget::default_tokenizer();
+                let fixed_tokens: usize = messages
+                    .iter()
+                    .map(|m| tokenizer.count_tokens(&serde_json::to_string(m).unwrap_or_default()))
+                    .sum();
+                let entries: Vec<_> = tool_calls
+                    .iter()
+                    .map(|call| super::context_budget::ContextEntry {
+                        name: call.id.clone(),
+                        text: results[&call.id].clone(),
+                    })
+                    .collect();
+                let force_types = deopt.config.force_types.as_deref().unwrap_or(&[]);
+                let kept: HashSet<String> =
+                    super::context_budget::trim_to_budget(entries, force_types, "", fixed_tokens, tokenizer)
+                        .into_iter()
+                        .map(|entry| entry.name)
+                        .collect();
+
+                for call in tool_calls {
+                    let content = if kept.contains(&call.id) {
+                        results[&call.id].clone()
+                    } else {
+                        format!(
+                            "Omitted: {} result exceeded the OPENAI_CONTEXT_LIMIT token budget",
+                            call.function.name
+                        )
+                    };
+                    messages.push(
+                        ChatCompletionRequestToolMessageArgs::default()
+                            .tool_call_id(call.id.clone())
+                            .content(content)
+                            .build()?
+                            .into(),
+                    );
+                }
+                continue;
+            }
+        }
+
+        let content = message
+            .content
+            .as_ref()
+            .ok_or_else(|| eyre::eyre!("Model returned neither tool calls nor content"))?;
+        let content = strip_json_code_fence(content);
+        let parsed: serde_json::Value = serde_json::from_str(&content)?;
+        super::usage::record(&config::get_openai_model_name(), &total_usage)?;
+        return Ok((parsed, total_usage));
+    }
+
+    Err(eyre::eyre!(
+        "Exceeded {MAX_TOOL_STEPS} tool-calling steps without a final structured response"
+    ))
 }
 
+/// Eagerly gather the same header/README context `generate_json_with_tools` pulls in on demand
+/// (see `call_header_tool`), for backends that can't drive that tool-calling loop themselves
+/// (Azure OpenAI, Claude, and OpenAI-compatible endpoints don't share OpenAI's tool-calling wire
+/// format here). This is the pre-chunk1-2 behavior of stuffing every header and the README into
+/// the prompt up front, kept around as the fallback for those backends.
+///
+/// Unlike the tool-calling loop, none of these backends get a chance to omit a result once it's
+/// been requested, so the same `OPENAI_CONTEXT_LIMIT` budget is enforced here up front: `entries`
+/// are trimmed against `base_prompt`'s token count (the text this context will be appended to)
+/// before being joined, instead of concatenating every header and the README unconditionally.
+pub(crate) fn gather_header_context(deopt: &Deopt, base_prompt: &str) -> String {
+    let Ok(files) = call_header_tool("list_headers", "", deopt) else {
+        return String::new();
+    };
+    let paths: Vec<String> = serde_json::from_str(&files).unwrap_or_default();
+
+    let mut entries = Vec::new();
+    for path in &paths {
+        let args = json!({ "path": path }).to_string();
+        if let Ok(content) = call_header_tool("read_header", &args, deopt) {
+            entries.push(super::context_budget::ContextEntry {
+                name: path.clone(),
+                text: format!("\n--- Header File: {path} ---\n{content}"),
+            });
+        }
+    }
+    if let Ok(docs) = call_header_tool("read_docs", "", deopt) {
+        entries.push(super::context_budget::ContextEntry {
+            name: "README".to_string(),
+            text: format!("\n--- Documentation File ---\n{docs}"),
+        });
+    }
+
+    let tokenizer = super::context_budget::default_tokenizer();
+    let fixed_tokens = tokenizer.count_tokens(base_prompt);
+    let force_types = deopt.config.force_types.as_deref().unwrap_or(&[]);
+    super::context_budget::trim_to_budget(entries, force_types, "", fixed_tokens, tokenizer)
+        .into_iter()
+        .map(|entry| entry.text)
+        .collect()
+}
 
 fn strip_code_prefix<'a>(input: &'a str, pat: &str) -> &'a str {
     let pat = String::from_iter(["```", pat]);
@@ -349,7 +633,7 @@ fn strip_code_prefix<'a>(input: &'a str, pat: &str) -> &'a str {
 }
 
 /// strip the code wrapper that ChatGPT generated with code.
-fn strip_code_wrapper(input: &str) -> String {
+pub(crate) fn strip_code_wrapper(input: &str) -> String {
     let mut input = input.trim();
     let mut event = "";
     if let Some(idx) = input.find("```") {
@@ -371,27 +655,40 @@ fn strip_code_wrapper(input: &str) -> String {
     ["/*", event, "*/\n", input].concat()
 }
 
+/// Strip a ```` ```json ```` / ```` ``` ```` fence a model wrapped its structured-output answer
+/// in, so `generate_json` can `serde_json::from_str` the result. Only OpenAI's own `generate_json`
+/// is guaranteed fence-free (it asks for `response_format: json_schema`); Claude's Messages API
+/// and generic OpenAI-compatible endpoints have no schema-enforcement equivalent and routinely
+/// fence a JSON answer without an explicit instruction forbidding it. A no-op when there's no
+/// fence to strip.
+pub(crate) fn strip_json_code_fence(input: &str) -> String {
+    let input = input.trim();
+    let Some(input) = input.strip_prefix("```") else {
+        return input.to_string();
+    };
+    let input = input.strip_prefix("json").unwrap_or(input);
+    let input = input.strip_prefix("JSON").unwrap_or(input);
+    let input = input.strip_prefix('\n').unwrap_or(input);
+    input.strip_suffix("```").unwrap_or(input).trim().to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use async_openai::types::{ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs};
-    use eyre::Result;
-
-    #[tokio::test]  // async test
-    async fn test_generate_json() -> Result<()> {
-        dotenv::dotenv().ok(); // make sure OPENAI_API_KEY is loaded
-        config::init_openai_env();
-        println!("API_KEY: {:?}", std::env::var("OPENAI_API_KEY"));
-	println!("MODEL: {:?}", std::env::var("OPENAI_MODEL_NAME"));
 
-        let prompt = "Explain Rust's ownership system in JSON format.".to_string();
-
-        // call your function
-        let (json, usage) = generate_json_by_chat(prompt, None).await?;
-
-        println!("JSON response:\n{}", serde_json::to_string_pretty(&json)?);
-        println!("Token usage: {:?}", usage);
+    #[test]
+    fn test_backoff_delay_grows_then_caps_at_30s() {
+        let first = backoff_delay(0);
+        let later = backoff_delay(3);
+        let capped = backoff_delay(20);
+        assert!(first < later, "delay should grow with the attempt number");
+        assert!(capped <= Duration::from_secs(30), "delay must stay capped at 30s");
+        assert!(first < Duration::from_secs(30));
+    }
 
-        Ok(())
+    #[test]
+    fn test_strip_json_code_fence_strips_language_tag_and_fence() {
+        assert_eq!(strip_json_code_fence("```json\n{\"a\": 1}\n```"), "{\"a\": 1}");
+        assert_eq!(strip_json_code_fence("{\"a\": 1}"), "{\"a\": 1}");
     }
 }
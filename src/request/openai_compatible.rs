@@ -0,0 +1,124 @@
+use crate::{
+    config::{self, ClientConfig},
+    deopt::Deopt,
+    is_critical_err,
+    program::Program,
+    FuzzerError,
+};
+use async_openai::{config::OpenAIConfig, types::CreateChatCompletionRequestArgs, Client};
+use eyre::Result;
+use futures::future::join_all;
+
+use super::{
+    openai::{strip_code_wrapper, strip_json_code_fence, TokenUsage},
+    Handler,
+};
+
+/// `Handler` for any endpoint that speaks the OpenAI chat-completions wire format without being
+/// OpenAI itself (vLLM, Ollama's OpenAI-compatible route, local proxies, etc). This only needs
+/// a custom `api_base`/`api_key` on top of `async_openai`'s `OpenAIConfig` -- no request/response
+/// shape differs from `openai::OpenAIHanler`.
+pub struct OpenAICompatibleHandler {
+    client: Client<OpenAIConfig>,
+    rt: tokio::runtime::Runtime,
+}
+
+impl OpenAICompatibleHandler {
+    pub fn new(config: &ClientConfig) -> Result<Self> {
+        let ClientConfig::OpenAICompatible { api_key, api_base, .. } = config else {
+            eyre::bail!("OpenAICompatibleHandler constructed from a non-openai-compatible client config");
+        };
+        let http_client = config::build_http_client(config.extra())?;
+        let mut openai_config = OpenAIConfig::new().with_api_base(api_base);
+        if let Some(api_key) = api_key {
+            openai_config = openai_config.with_api_key(api_key);
+        }
+        let client = Client::with_config(openai_config).with_http_client(http_client);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap_or_else(|_| panic!("Unable to build the openai-compatible runtime."));
+        Ok(Self { client, rt })
+    }
+
+    async fn get_chat_response(
+        &self,
+        request: async_openai::types::CreateChatCompletionRequest,
+    ) -> Result<async_openai::types::CreateChatCompletionResponse> {
+        for retry in 0..config::RETRY_N {
+            let response = self
+                .client
+                .chat()
+                .create(request.clone())
+                .await
+                .map_err(eyre::Report::new);
+            match is_critical_err(&response) {
+                crate::Critical::Normal => return Ok(response?),
+                crate::Critical::NonCritical => {
+                    let delay = crate::request::openai::backoff_delay(retry);
+                    log::warn!("OpenAI-compatible request failed (attempt {}/{}), backing off {:.1}s before retry", retry + 1, config::RETRY_N, delay.as_secs_f32());
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+                crate::Critical::Critical => return Err(response.err().unwrap()),
+            }
+        }
+        Err(FuzzerError::RetryError(format!("{request:?}"), config::RETRY_N).into())
+    }
+}
+
+impl Handler for OpenAICompatibleHandler {
+    fn generate(&self, prompt: &super::prompt::Prompt) -> eyre::Result<Vec<Program>> {
+        let chat_msgs = prompt.to_chatgpt_message();
+        let mut futures = Vec::new();
+        for _ in 0..config::get_config().n_sample {
+            let request = CreateChatCompletionRequestArgs::default()
+                .model(config::get_openai_model_name())
+                .messages(chat_msgs.clone())
+                .temperature(config::get_config().temperature)
+                .build()?;
+            futures.push(self.get_chat_response(request));
+        }
+        let results = self.rt.block_on(join_all(futures));
+
+        let mut programs = Vec::new();
+        let mut total_usage = TokenUsage::default();
+        for result in results {
+            let response = result?;
+            total_usage.add(&TokenUsage::from_response(&response));
+            let choice = response.choices.first().unwrap();
+            let content = choice.message.content.as_ref().unwrap();
+            programs.push(Program::new(&strip_code_wrapper(content)));
+        }
+        log::info!(
+            "OpenAI-compatible Token Usage - Prompt: {}, Completion: {}, Total: {}",
+            total_usage.prompt_tokens,
+            total_usage.completion_tokens,
+            total_usage.total_tokens
+        );
+        super::usage::record(&config::get_openai_model_name(), &total_usage)?;
+        Ok(programs)
+    }
+
+    fn generate_json(&self, prompt: String, deopt: &Deopt) -> eyre::Result<serde_json::Value> {
+        self.rt.block_on(async {
+            let context = super::openai::gather_header_context(deopt, &prompt);
+            let prompt = format!("{prompt}\n{context}");
+            let user_msg = async_openai::types::ChatCompletionRequestUserMessageArgs::default()
+                .content(prompt)
+                .build()?
+                .into();
+            let request = CreateChatCompletionRequestArgs::default()
+                .model(config::get_openai_model_name())
+                .messages(vec![user_msg])
+                .temperature(config::get_config().temperature)
+                .build()?;
+            let response = self.get_chat_response(request).await?;
+            let usage = TokenUsage::from_response(&response);
+            super::usage::record(&config::get_openai_model_name(), &usage)?;
+            let choice = response.choices.first().unwrap();
+            let content = choice.message.content.as_ref().unwrap();
+            Ok(serde_json::from_str(&strip_json_code_fence(content))?)
+        })
+    }
+}
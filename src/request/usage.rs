@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use once_cell::sync::OnceCell;
+
+use super::openai::TokenUsage;
+
+/// Where running campaign totals are persisted, relative to the working directory.
+pub const USAGE_FILE: &str = "token_usage.json";
+
+/// Cumulative usage for one model across the whole campaign.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ModelUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct CampaignUsage {
+    pub by_model: HashMap<String, ModelUsage>,
+}
+
+fn usage_lock() -> &'static RwLock<CampaignUsage> {
+    static CAMPAIGN_USAGE: OnceCell<RwLock<CampaignUsage>> = OnceCell::new();
+    CAMPAIGN_USAGE.get_or_init(|| {
+        let loaded = std::fs::read_to_string(USAGE_FILE)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        RwLock::new(loaded)
+    })
+}
+
+/// Per-million-token (prompt, completion) USD prices. Looked up by exact model name; an unknown
+/// model reports $0 cost rather than failing a run over a missing price entry.
+fn model_rates(model: &str) -> (f64, f64) {
+    match model {
+        "gpt-4o" => (2.50, 10.00),
+        "gpt-4o-mini" => (0.15, 0.60),
+        "gpt-4-turbo" | "gpt-4" => (10.00, 30.00),
+        "claude-3-5-sonnet-20241022" | "claude-3-5-sonnet-latest" => (3.00, 15.00),
+        "claude-3-haiku-20240307" => (0.25, 1.25),
+        _ => (0.0, 0.0),
+    }
+}
+
+pub fn cost_usd(model: &str, usage: &ModelUsage) -> f64 {
+    let (prompt_rate, completion_rate) = model_rates(model);
+    (usage.prompt_tokens as f64 / 1_000_000.0) * prompt_rate
+        + (usage.completion_tokens as f64 / 1_000_000.0) * completion_rate
+}
+
+/// Optional hard ceiling on total campaign spend, read from `FUZZ_BUDGET_USD`.
+fn budget_cap_usd() -> Option<f64> {
+    std::env::var("FUZZ_BUDGET_USD").ok().and_then(|s| s.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_usd_known_model_prices_both_token_kinds() {
+        let usage = ModelUsage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 1_000_000,
+            total_tokens: 2_000_000,
+        };
+        assert_eq!(cost_usd("gpt-4o", &usage), 12.50);
+    }
+
+    #[test]
+    fn test_cost_usd_unknown_model_is_free() {
+        let usage = ModelUsage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 1_000_000,
+            total_tokens: 2_000_000,
+        };
+        assert_eq!(cost_usd("some-unpriced-model", &usage), 0.0);
+    }
+}
+
+/// Record `usage` against `model`'s running campaign total, persist it to `token_usage.json`,
+/// and enforce the optional `FUZZ_BUDGET_USD` cap. Called from both the program-sampling and
+/// structured-extraction chat paths so the campaign-wide total covers every LLM call, not just
+/// one kind of request.
+pub fn record(model: &str, usage: &TokenUsage) -> eyre::Result<()> {
+    let mut campaign = usage_lock().write().unwrap();
+    let entry = campaign.by_model.entry(model.to_string()).or_default();
+    entry.prompt_tokens += usage.prompt_tokens as u64;
+    entry.completion_tokens += usage.completion_tokens as u64;
+    entry.total_tokens += usage.total_tokens as u64;
+
+    let total_cost: f64 = campaign.by_model.iter().map(|(m, u)| cost_usd(m, u)).sum();
+    log::info!("Campaign usage for {model}: {entry:?} (running cost: ${total_cost:.4})");
+
+    let text = serde_json::to_string_pretty(&*campaign)?;
+    if let Err(e) = std::fs::write(USAGE_FILE, text) {
+        log::warn!("Could not persist {USAGE_FILE}: {e}");
+    }
+
+    if let Some(cap) = budget_cap_usd() {
+        if total_cost > cap {
+            eyre::bail!(
+                "Campaign budget of ${cap:.2} exceeded (spent ${total_cost:.4}); aborting further generation"
+            );
+        }
+    }
+    Ok(())
+}